@@ -0,0 +1,7 @@
+pub mod render;
+pub mod render_target;
+pub mod tessellator;
+pub mod types;
+pub mod util;
+
+pub use render::render_to_png;
@@ -0,0 +1,335 @@
+//! Offscreen rendering: rasterize an SVG straight to a PNG file, without a
+//! `winit` window, for use as a library or from the CLI (e.g. CI reference
+//! images, batch conversion jobs).
+
+use std::error::Error;
+use std::path::Path;
+
+use crate::render_target::{RenderTarget, TextureTarget};
+use crate::tessellator::artifacts::DrawCommand;
+use crate::tessellator::LyonTessellator;
+use crate::types::{BufferState, GpuGlobals, GpuLayerGlobals, ImageBufferState};
+use crate::util::{
+    build_buffers, build_image_buffers, build_image_pipeline,
+    build_layer_pipeline, build_pipelines, get_globals, LayerPipelines,
+    MaskPipelines,
+};
+use wgpu::util::DeviceExt;
+use wgpu::RenderPipeline;
+
+pub fn render_to_png(
+    svg_content: &String,
+    width: u32,
+    height: u32,
+    path: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pollster::block_on(render_to_png_async(svg_content, width, height, path))
+}
+
+async fn render_to_png_async(
+    svg_content: &String,
+    width: u32,
+    height: u32,
+    path: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let scene = get_globals(svg_content);
+
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await
+        .ok_or("no suitable wgpu adapter found")?;
+    let (device, queue) =
+        adapter.request_device(&wgpu::DeviceDescriptor::default(), None).await?;
+
+    let mut tessellator = LyonTessellator::new();
+    tessellator.init(svg_content);
+    let data = tessellator.tessellate()?;
+
+    let buffers = build_buffers(&device, &queue, &data);
+    let pipelines =
+        build_pipelines(&device, &buffers, false, TextureTarget::FORMAT);
+    let layer_pipelines = build_layer_pipeline(&device, TextureTarget::FORMAT);
+    let image_buffers = build_image_buffers(&device, &queue, &data);
+    let image_pipeline = build_image_pipeline(
+        &device,
+        &buffers.bind_group_layout,
+        &image_buffers.bind_group_layout,
+        TextureTarget::FORMAT,
+    );
+    let target = TextureTarget::new(&device, width, height);
+
+    let globals = GpuGlobals {
+        zoom: scene.zoom,
+        _pad0: 0.0,
+        pan: scene.pan,
+        aspect_ratio: width as f32 / height as f32,
+        _pad1: [0.0; 3],
+        color_transform: scene.color_transform,
+    };
+    queue.write_buffer(&buffers.globals_ubo, 0, bytemuck::bytes_of(&globals));
+
+    let mut encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("render_to_png") },
+    );
+
+    render_commands(
+        &device,
+        &mut encoder,
+        &target,
+        &buffers,
+        &pipelines,
+        &layer_pipelines,
+        &image_pipeline,
+        &image_buffers,
+        &data.commands,
+    );
+
+    target.copy_to_buffer(&mut encoder);
+    queue.submit(Some(encoder.finish()));
+
+    let pixels = target.read_rgba(&device).await;
+    let image: image::RgbaImage = image::ImageBuffer::from_raw(width, height, pixels)
+        .ok_or("rendered buffer did not match the expected image dimensions")?;
+    image.save(path)?;
+
+    Ok(())
+}
+
+/// Plays `commands` back against `target`, splitting at `PushLayer`/
+/// `PopLayer` pairs: the bracketed range renders into its own offscreen
+/// `TextureTarget` first (via a recursive call), which is then composited
+/// back onto `target` as a single translucent layer. This keeps overlapping
+/// shapes inside an opacity group from double-blending against each other,
+/// while shapes outside any group still draw straight into `target`.
+fn render_commands(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    target: &dyn RenderTarget,
+    buffers: &BufferState,
+    pipelines: &MaskPipelines,
+    layer_pipelines: &LayerPipelines,
+    image_pipeline: &RenderPipeline,
+    image_buffers: &ImageBufferState,
+    commands: &[DrawCommand],
+) {
+    let mut mask_stack: Vec<u32> = Vec::new();
+    let mut cleared = false;
+    let mut i = 0;
+
+    while i < commands.len() {
+        if let DrawCommand::PushLayer { opacity } = commands[i] {
+            let end = matching_pop_layer(commands, i);
+
+            let layer_target = TextureTarget::new(device, target.width(), target.height());
+            render_commands(
+                device,
+                encoder,
+                &layer_target,
+                buffers,
+                pipelines,
+                layer_pipelines,
+                image_pipeline,
+                image_buffers,
+                &commands[i + 1..end],
+            );
+
+            composite_layer(
+                device,
+                encoder,
+                target,
+                &layer_target,
+                layer_pipelines,
+                opacity,
+                *mask_stack.last().unwrap_or(&0),
+                !cleared,
+            );
+            cleared = true;
+
+            i = end + 1;
+            continue;
+        }
+
+        let run_start = i;
+        while i < commands.len() && !matches!(commands[i], DrawCommand::PushLayer { .. })
+        {
+            i += 1;
+        }
+        draw_run(
+            encoder,
+            target,
+            buffers,
+            pipelines,
+            image_pipeline,
+            image_buffers,
+            &commands[run_start..i],
+            &mut mask_stack,
+            !cleared,
+        );
+        cleared = true;
+    }
+
+    if !cleared {
+        clear_target(encoder, target);
+    }
+}
+
+/// Draws one contiguous, layer-free run of commands in a single render pass,
+/// clearing `target` first the first time a run (or layer) touches it and
+/// loading its existing contents on every call after.
+fn draw_run(
+    encoder: &mut wgpu::CommandEncoder,
+    target: &dyn RenderTarget,
+    buffers: &BufferState,
+    pipelines: &MaskPipelines,
+    image_pipeline: &RenderPipeline,
+    image_buffers: &ImageBufferState,
+    commands: &[DrawCommand],
+    mask_stack: &mut Vec<u32>,
+    clear: bool,
+) {
+    let mut pass = begin_pass(encoder, target, clear);
+    pass.set_bind_group(0, &buffers.bind_group, &[]);
+
+    for command in commands {
+        match *command {
+            DrawCommand::PushMask { index_start, index_count } => {
+                let parent_depth = *mask_stack.last().unwrap_or(&0);
+                pass.set_pipeline(&pipelines.write_mask);
+                pass.set_vertex_buffer(0, buffers.vbo.slice(..));
+                pass.set_index_buffer(buffers.ibo.slice(..), wgpu::IndexFormat::Uint32);
+                pass.set_stencil_reference(parent_depth);
+                pass.draw_indexed(index_start..(index_start + index_count), 0, 0..1);
+                mask_stack.push(parent_depth + 1);
+            }
+            DrawCommand::PopMask { index_start, index_count } => {
+                let child_depth = mask_stack.pop().unwrap_or(0);
+                pass.set_pipeline(&pipelines.clear_mask);
+                pass.set_vertex_buffer(0, buffers.vbo.slice(..));
+                pass.set_index_buffer(buffers.ibo.slice(..), wgpu::IndexFormat::Uint32);
+                pass.set_stencil_reference(child_depth);
+                pass.draw_indexed(index_start..(index_start + index_count), 0, 0..1);
+            }
+            DrawCommand::Draw { index_start, index_count } => {
+                pass.set_pipeline(&pipelines.draw);
+                pass.set_vertex_buffer(0, buffers.vbo.slice(..));
+                pass.set_index_buffer(buffers.ibo.slice(..), wgpu::IndexFormat::Uint32);
+                pass.set_stencil_reference(*mask_stack.last().unwrap_or(&0));
+                pass.draw_indexed(index_start..(index_start + index_count), 0, 0..1);
+            }
+            DrawCommand::DrawImage { draw_index } => {
+                let draw = &image_buffers.draws[draw_index as usize];
+                pass.set_pipeline(image_pipeline);
+                pass.set_vertex_buffer(0, image_buffers.image_vbo.slice(..));
+                pass.set_bind_group(1, &draw.bind_group, &[]);
+                pass.set_stencil_reference(*mask_stack.last().unwrap_or(&0));
+                pass.draw(draw.vertex_start..(draw.vertex_start + draw.vertex_count), 0..1);
+            }
+            DrawCommand::PushLayer { .. } | DrawCommand::PopLayer => {
+                unreachable!("layer-free run")
+            }
+        }
+    }
+}
+
+/// Composites `layer` back onto `target`, scaled by `opacity` and clipped by
+/// the clip mask (if any) active at the `PushLayer` site.
+fn composite_layer(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    target: &dyn RenderTarget,
+    layer: &TextureTarget,
+    layer_pipelines: &LayerPipelines,
+    opacity: f32,
+    stencil_reference: u32,
+    clear: bool,
+) {
+    let globals_ubo = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Layer opacity ubo"),
+        contents: bytemuck::bytes_of(&GpuLayerGlobals { opacity }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Layer sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Layer bind group"),
+        layout: &layer_pipelines.bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_ubo.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(layer.resolve_view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+    });
+
+    let mut pass = begin_pass(encoder, target, clear);
+    pass.set_pipeline(&layer_pipelines.composite);
+    pass.set_stencil_reference(stencil_reference);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    target: &'a dyn RenderTarget,
+    clear: bool,
+) -> wgpu::RenderPass<'a> {
+    let load = if clear {
+        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+    } else {
+        wgpu::LoadOp::Load
+    };
+    let stencil_load = if clear { wgpu::LoadOp::Clear(0) } else { wgpu::LoadOp::Load };
+
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("render_commands pass"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: target.msaa_view(),
+            resolve_target: Some(target.resolve_view()),
+            ops: wgpu::Operations { load, store: true },
+        }],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: target.stencil_view(),
+            depth_ops: None,
+            stencil_ops: Some(wgpu::Operations { load: stencil_load, store: true }),
+        }),
+    })
+}
+
+/// Clears `target` without drawing anything, for the degenerate case of an
+/// empty command list: an opacity group with no renderable children, or a
+/// scene with nothing to draw.
+fn clear_target(encoder: &mut wgpu::CommandEncoder, target: &dyn RenderTarget) {
+    begin_pass(encoder, target, true);
+}
+
+/// Finds the `PopLayer` matching the `PushLayer` at `start`, accounting for
+/// nested opacity groups in between.
+fn matching_pop_layer(commands: &[DrawCommand], start: usize) -> usize {
+    let mut depth = 0;
+    for (offset, command) in commands[start..].iter().enumerate() {
+        match command {
+            DrawCommand::PushLayer { .. } => depth += 1,
+            DrawCommand::PopLayer => {
+                depth -= 1;
+                if depth == 0 {
+                    return start + offset;
+                }
+            }
+            _ => {}
+        }
+    }
+    panic!("PushLayer without a matching PopLayer");
+}
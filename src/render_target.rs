@@ -0,0 +1,262 @@
+//! Where a frame's color (and MSAA resolve) attachments come from: an
+//! on-screen swapchain surface, or an offscreen texture that can be read
+//! back to the CPU.
+
+use crate::util::{MSAA_SAMPLES, STENCIL_FORMAT};
+
+/// A place the render passes begun in `crate::render` can draw into.
+pub trait RenderTarget {
+    fn msaa_view(&self) -> &wgpu::TextureView;
+    fn resolve_view(&self) -> &wgpu::TextureView;
+    fn stencil_view(&self) -> &wgpu::TextureView;
+    fn format(&self) -> wgpu::TextureFormat;
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+}
+
+/// The existing on-screen path: draws into the current swapchain frame,
+/// resolving the MSAA attachment onto it.
+pub struct SurfaceTarget {
+    msaa_view: wgpu::TextureView,
+    surface_view: wgpu::TextureView,
+    stencil_view: wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+}
+
+impl SurfaceTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_view: wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let msaa_view = create_msaa_view(device, format, width, height);
+        let stencil_view = create_stencil_view(device, width, height);
+        SurfaceTarget {
+            msaa_view,
+            surface_view,
+            stencil_view,
+            format,
+            width,
+            height,
+        }
+    }
+}
+
+impl RenderTarget for SurfaceTarget {
+    fn msaa_view(&self) -> &wgpu::TextureView {
+        &self.msaa_view
+    }
+
+    fn resolve_view(&self) -> &wgpu::TextureView {
+        &self.surface_view
+    }
+
+    fn stencil_view(&self) -> &wgpu::TextureView {
+        &self.stencil_view
+    }
+
+    fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// Row byte alignment wgpu requires for `copy_texture_to_buffer`.
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+fn align_up(value: u32, alignment: u32) -> u32 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// An offscreen render target that can be read back to a tightly-packed RGBA
+/// buffer, for rasterizing an SVG to an image file without a window.
+pub struct TextureTarget {
+    msaa_view: wgpu::TextureView,
+    resolve_texture: wgpu::Texture,
+    resolve_view: wgpu::TextureView,
+    stencil_view: wgpu::TextureView,
+    output_buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl TextureTarget {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let msaa_view = create_msaa_view(device, Self::FORMAT, width, height);
+        let stencil_view = create_stencil_view(device, width, height);
+
+        let resolve_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("TextureTarget resolve texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+        let resolve_view =
+            resolve_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let padded_bytes_per_row = align_up(width * 4, COPY_BYTES_PER_ROW_ALIGNMENT);
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("TextureTarget readback buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        TextureTarget {
+            msaa_view,
+            resolve_texture,
+            resolve_view,
+            stencil_view,
+            output_buffer,
+            format: Self::FORMAT,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Records a copy of the resolved frame into the mappable readback
+    /// buffer on `encoder`. Call after recording the render passes and
+    /// before submitting `encoder`; `read_rgba` then reads the result.
+    pub fn copy_to_buffer(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.resolve_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(
+                        self.padded_bytes_per_row,
+                    ),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Maps the readback buffer and strips wgpu's row padding, returning
+    /// tightly-packed RGBA8 bytes (`width * height * 4`).
+    pub async fn read_rgba(&self, device: &wgpu::Device) -> Vec<u8> {
+        let slice = self.output_buffer.slice(..);
+        let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            tx.send(result).unwrap();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.receive().await.unwrap().unwrap();
+
+        let padded = slice.get_mapped_range();
+        let unpadded_bytes_per_row = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(unpadded_bytes_per_row * self.height as usize);
+        for row in padded.chunks(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row]);
+        }
+
+        drop(padded);
+        self.output_buffer.unmap();
+        pixels
+    }
+}
+
+impl RenderTarget for TextureTarget {
+    fn msaa_view(&self) -> &wgpu::TextureView {
+        &self.msaa_view
+    }
+
+    fn resolve_view(&self) -> &wgpu::TextureView {
+        &self.resolve_view
+    }
+
+    fn stencil_view(&self) -> &wgpu::TextureView {
+        &self.stencil_view
+    }
+
+    fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+fn create_stencil_view(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Stencil attachment"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: MSAA_SAMPLES,
+        dimension: wgpu::TextureDimension::D2,
+        format: STENCIL_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_msaa_view(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("MSAA attachment"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: MSAA_SAMPLES,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
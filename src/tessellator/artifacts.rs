@@ -0,0 +1,68 @@
+use super::types::{
+    GpuGradient, GpuGradientStop, GpuImageVertex, GpuPrimitive, GpuTransform,
+    GpuVertex,
+};
+
+/// The decoded pixels of an embedded `<image>`, along with the filtering its
+/// `image-rendering` hint implies.
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+    pub smooth: bool,
+}
+
+/// The vertex range (a quad, as two triangles) belonging to one embedded
+/// image, and the index into `TessellationData::images` for its pixels.
+#[derive(Copy, Clone, Debug)]
+pub struct ImageDraw {
+    pub image_index: u32,
+    pub vertex_start: u32,
+    pub vertex_count: u32,
+}
+
+/// A step in the draw order, in the order `usvg` nodes were visited. The
+/// renderer plays these back against the index buffer, switching pipeline
+/// and stencil reference as masks are pushed and popped.
+#[derive(Copy, Clone, Debug)]
+pub enum DrawCommand {
+    /// Render the clip geometry at `[index_start, index_start + index_count)`
+    /// with the "write mask" pipeline, incrementing the stencil buffer.
+    PushMask { index_start: u32, index_count: u32 },
+    /// Render the same clip geometry again with the "clear mask" pipeline,
+    /// decrementing the stencil buffer back to its value before the push.
+    PopMask { index_start: u32, index_count: u32 },
+    /// Render ordinary (possibly masked) fill/stroke geometry at
+    /// `[index_start, index_start + index_count)` with the "draw" pipeline.
+    Draw { index_start: u32, index_count: u32 },
+    /// Begin a translucent group: everything up to the matching `PopLayer`
+    /// renders into an offscreen layer first, which is then composited back
+    /// as a whole at `opacity`, so overlapping children within the group
+    /// don't double-blend against each other.
+    PushLayer { opacity: f32 },
+    /// Composite the layer opened by the matching `PushLayer` back onto its
+    /// parent target.
+    PopLayer,
+    /// Render the embedded image at `TessellationData::image_draws[draw_index]`
+    /// through the image pipeline, bound to its own texture/sampler bind
+    /// group. Interleaving this into the command stream (rather than drawing
+    /// every image in one trailing pass) lets images inherit the ambient
+    /// clip mask and opacity-group layer in effect at their position in the
+    /// document, the same as ordinary path geometry.
+    DrawImage { draw_index: u32 },
+}
+
+/// Everything `crate::util::build_buffers` needs to upload a tessellated
+/// scene to the GPU.
+pub struct TessellationData {
+    pub vertices: Vec<GpuVertex>,
+    pub indices: Vec<u32>,
+    pub transforms: Vec<GpuTransform>,
+    pub primitives: Vec<GpuPrimitive>,
+    pub gradients: Vec<GpuGradient>,
+    pub gradient_stops: Vec<GpuGradientStop>,
+    pub commands: Vec<DrawCommand>,
+    pub images: Vec<ImageData>,
+    pub image_vertices: Vec<GpuImageVertex>,
+    pub image_draws: Vec<ImageDraw>,
+}
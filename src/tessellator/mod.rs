@@ -0,0 +1,5 @@
+pub mod artifacts;
+pub mod tessellator;
+pub mod types;
+
+pub use tessellator::LyonTessellator;
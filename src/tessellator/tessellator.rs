@@ -13,8 +13,12 @@ use usvg::{NodeExt, Tree, ViewBox};
 
 use crate::tessellator::types::GpuColor;
 
-use super::artifacts::TessellationData;
-use super::types::{GpuPrimitive, GpuTransform, GpuVertex};
+use super::artifacts::{DrawCommand, ImageData, ImageDraw, TessellationData};
+use super::types::{
+    GpuGradient, GpuGradientStop, GpuImageVertex, GpuPrimitive, GpuTransform,
+    GpuVertex, GRADIENT_KIND_LINEAR, GRADIENT_KIND_RADIAL, SPREAD_PAD,
+    SPREAD_REFLECT, SPREAD_REPEAT,
+};
 
 const TOLERANCE: f32 = 0.1;
 
@@ -56,92 +60,335 @@ impl LyonTessellator {
     pub fn tessellate(
         &self,
     ) -> Result<TessellationData, Box<dyn Error + Send + Sync>> {
-        // Create vertex buffer
-        let mut fill_tess = FillTessellator::new();
-        let mut stroke_tess = StrokeTessellator::new();
-        let mut mesh: VertexBuffers<_, u32> = VertexBuffers::new();
-
-        let mut transforms = Vec::new();
-        let mut primitives = Vec::new();
-
-        let mut prev_transform = usvg::Transform {
-            a: NAN,
-            b: NAN,
-            c: NAN,
-            d: NAN,
-            e: NAN,
-            f: NAN,
+        let mut ctx = TessCtx::new();
+
+        for child in self.state.as_ref().unwrap().rtree.root().children() {
+            visit_node(&mut ctx, &child);
+        }
+
+        let data = TessellationData {
+            vertices: ctx.mesh.vertices,
+            indices: ctx.mesh.indices,
+            transforms: ctx.transforms,
+            primitives: ctx.primitives,
+            gradients: ctx.gradients,
+            gradient_stops: ctx.gradient_stops,
+            commands: ctx.commands,
+            images: ctx.images,
+            image_vertices: ctx.image_vertices,
+            image_draws: ctx.image_draws,
         };
 
-        for node in self.state.as_ref().unwrap().rtree.root().descendants() {
-            if let usvg::NodeKind::Path(ref p) = *node.borrow() {
-                let t = node.transform();
-                if t != prev_transform {
-                    transforms.push(GpuTransform {
-                        data0: [t.a as f32, t.b as f32, t.c as f32, t.d as f32],
-                        data1: [t.e as f32, t.f as f32, 0.0, 0.0],
-                    });
-                }
-                prev_transform = t;
-
-                let transform_idx = transforms.len() as u32 - 1;
-
-                if let Some(ref fill) = p.fill {
-                    // fall back to always use color fill
-                    // no gradients (yet?)
-                    let color = match fill.paint {
-                        usvg::Paint::Color(c) => GpuColor {
-                            red: c.red,
-                            green: c.green,
-                            blue: c.blue,
-                            alpha: (fill.opacity.value() * 255_f64) as u8,
-                        },
-                        _ => FALLBACK_COLOR,
-                    };
+        // Return result
+        Ok(data)
+    }
+}
 
-                    primitives.push(GpuPrimitive::new(transform_idx, color));
-
-                    fill_tess
-                        .tessellate(
-                            convert_path(p),
-                            &FillOptions::tolerance(TOLERANCE),
-                            &mut BuffersBuilder::new(
-                                &mut mesh,
-                                VertexCtor {
-                                    prim_id: primitives.len() as u32 - 1,
-                                },
-                            ),
-                        )
-                        .expect("Error during tesselation!");
-                }
+/// Accumulates everything `tessellate` produces while `visit_node` walks the
+/// `usvg` tree.
+struct TessCtx {
+    fill_tess: FillTessellator,
+    stroke_tess: StrokeTessellator,
+    mesh: VertexBuffers<GpuVertex, u32>,
+    transforms: Vec<GpuTransform>,
+    primitives: Vec<GpuPrimitive>,
+    gradients: Vec<GpuGradient>,
+    gradient_stops: Vec<GpuGradientStop>,
+    commands: Vec<DrawCommand>,
+    images: Vec<ImageData>,
+    image_vertices: Vec<GpuImageVertex>,
+    image_draws: Vec<ImageDraw>,
+    prev_transform: usvg::Transform,
+}
+
+impl TessCtx {
+    fn new() -> Self {
+        TessCtx {
+            fill_tess: FillTessellator::new(),
+            stroke_tess: StrokeTessellator::new(),
+            mesh: VertexBuffers::new(),
+            transforms: Vec::new(),
+            primitives: Vec::new(),
+            gradients: Vec::new(),
+            gradient_stops: Vec::new(),
+            commands: Vec::new(),
+            images: Vec::new(),
+            image_vertices: Vec::new(),
+            image_draws: Vec::new(),
+            prev_transform: usvg::Transform {
+                a: NAN,
+                b: NAN,
+                c: NAN,
+                d: NAN,
+                e: NAN,
+                f: NAN,
+            },
+        }
+    }
+
+    /// Returns the `GpuTransform` index for `node`, pushing a new one only
+    /// when it differs from the previously visited node's transform.
+    fn transform_idx_for(&mut self, node: &usvg::Node) -> u32 {
+        let t = node.transform();
+        if t != self.prev_transform {
+            self.transforms.push(GpuTransform {
+                data0: [t.a as f32, t.b as f32, t.c as f32, t.d as f32],
+                data1: [t.e as f32, t.f as f32, 0.0, 0.0],
+            });
+        }
+        self.prev_transform = t;
+        self.transforms.len() as u32 - 1
+    }
+
+    fn tessellate_path(&mut self, p: &usvg::Path, transform_idx: u32) {
+        let index_start = self.mesh.indices.len() as u32;
 
-                if let Some(ref stroke) = p.stroke {
-                    let (stroke_color, stroke_opts) = convert_stroke(stroke);
-                    primitives
-                        .push(GpuPrimitive::new(transform_idx, stroke_color));
-                    let _ = stroke_tess.tessellate(
-                        convert_path(p),
-                        &stroke_opts.with_tolerance(TOLERANCE),
-                        &mut BuffersBuilder::new(
-                            &mut mesh,
-                            VertexCtor {
-                                prim_id: primitives.len() as u32 - 1,
-                            },
-                        ),
+        if let Some(ref fill) = p.fill {
+            match fill.paint {
+                usvg::Paint::Color(c) => {
+                    let color = GpuColor {
+                        red: c.red,
+                        green: c.green,
+                        blue: c.blue,
+                        alpha: (fill.opacity.value() * 255_f64) as u8,
+                    };
+                    self.primitives
+                        .push(GpuPrimitive::new(transform_idx, color));
+                }
+                usvg::Paint::LinearGradient(ref gradient) => {
+                    let gradient_id = push_linear_gradient(
+                        gradient,
+                        &mut self.gradients,
+                        &mut self.gradient_stops,
                     );
+                    self.primitives.push(GpuPrimitive::new_gradient(
+                        transform_idx,
+                        gradient_id,
+                    ));
                 }
-            }
+                usvg::Paint::RadialGradient(ref gradient) => {
+                    let gradient_id = push_radial_gradient(
+                        gradient,
+                        &mut self.gradients,
+                        &mut self.gradient_stops,
+                    );
+                    self.primitives.push(GpuPrimitive::new_gradient(
+                        transform_idx,
+                        gradient_id,
+                    ));
+                }
+                _ => {
+                    self.primitives
+                        .push(GpuPrimitive::new(transform_idx, FALLBACK_COLOR));
+                }
+            };
+
+            self.fill_tess
+                .tessellate(
+                    convert_path(p),
+                    &FillOptions::tolerance(TOLERANCE)
+                        .with_fill_rule(convert_fill_rule(fill.rule)),
+                    &mut BuffersBuilder::new(
+                        &mut self.mesh,
+                        VertexCtor {
+                            prim_id: self.primitives.len() as u32 - 1,
+                        },
+                    ),
+                )
+                .expect("Error during tesselation!");
         }
 
-        let data = TessellationData {
-            vertices: mesh.vertices,
-            indices: mesh.indices,
-            transforms,
-            primitives,
+        if let Some(ref stroke) = p.stroke {
+            let (stroke_color, stroke_opts) = convert_stroke(stroke);
+            self.primitives
+                .push(GpuPrimitive::new(transform_idx, stroke_color));
+            let _ = self.stroke_tess.tessellate(
+                convert_path(p),
+                &stroke_opts.with_tolerance(TOLERANCE),
+                &mut BuffersBuilder::new(
+                    &mut self.mesh,
+                    VertexCtor {
+                        prim_id: self.primitives.len() as u32 - 1,
+                    },
+                ),
+            );
+        }
+
+        let index_count = self.mesh.indices.len() as u32 - index_start;
+        if index_count > 0 {
+            self.commands.push(DrawCommand::Draw {
+                index_start,
+                index_count,
+            });
+        }
+    }
+
+    /// Tessellates a single clip-path shape, filling it with the fallback
+    /// color (the write/clear-mask pipelines disable color writes, so the
+    /// color itself is never seen).
+    fn tessellate_clip_shape(&mut self, p: &usvg::Path, transform_idx: u32) {
+        self.primitives
+            .push(GpuPrimitive::new(transform_idx, FALLBACK_COLOR));
+        self.fill_tess
+            .tessellate(
+                convert_path(p),
+                &FillOptions::tolerance(TOLERANCE),
+                &mut BuffersBuilder::new(
+                    &mut self.mesh,
+                    VertexCtor {
+                        prim_id: self.primitives.len() as u32 - 1,
+                    },
+                ),
+            )
+            .expect("Error during clip tesselation!");
+    }
+
+    /// Decodes an embedded `<image>`'s pixels and emits a textured quad
+    /// (two triangles) sized and positioned by its view box, honoring
+    /// `preserveAspectRatio`'s default "meet" fit.
+    fn tessellate_image(&mut self, img: &usvg::Image, node: &usvg::Node) {
+        let (width, height, rgba) = match decode_image(&img.kind) {
+            Some(decoded) => decoded,
+            None => return,
         };
 
-        // Return result
-        Ok(data)
+        let (x, y, w, h) =
+            fit_image_rect(&img.view_box, width as f64, height as f64);
+        let transform_id = self.transform_idx_for(node);
+
+        let image_index = self.images.len() as u32;
+        self.images.push(ImageData {
+            width,
+            height,
+            rgba,
+            smooth: img.rendering_mode != usvg::ImageRendering::OptimizeSpeed,
+        });
+
+        let top_left = ([x as f32, y as f32], [0.0, 0.0]);
+        let top_right = ([(x + w) as f32, y as f32], [1.0, 0.0]);
+        let bottom_right = ([(x + w) as f32, (y + h) as f32], [1.0, 1.0]);
+        let bottom_left = ([x as f32, (y + h) as f32], [0.0, 1.0]);
+
+        let vertex_start = self.image_vertices.len() as u32;
+        for (position, uv) in
+            [top_left, top_right, bottom_right, top_left, bottom_right, bottom_left]
+        {
+            self.image_vertices.push(GpuImageVertex {
+                position,
+                uv,
+                transform_id,
+            });
+        }
+        let vertex_count = self.image_vertices.len() as u32 - vertex_start;
+
+        let draw_index = self.image_draws.len() as u32;
+        self.image_draws.push(ImageDraw {
+            image_index,
+            vertex_start,
+            vertex_count,
+        });
+        self.commands.push(DrawCommand::DrawImage { draw_index });
+    }
+}
+
+/// Decodes a raster `<image>`'s bytes to tightly-packed RGBA8. Embedded
+/// nested SVGs are not rasterized here.
+fn decode_image(kind: &usvg::ImageKind) -> Option<(u32, u32, Vec<u8>)> {
+    let data = match kind {
+        usvg::ImageKind::PNG(data) | usvg::ImageKind::JPEG(data) => data,
+        usvg::ImageKind::GIF(data) => data,
+        usvg::ImageKind::SVG(_) => return None,
+    };
+
+    let decoded = image::load_from_memory(data).ok()?.to_rgba8();
+    let (width, height) = decoded.dimensions();
+    Some((width, height, decoded.into_raw()))
+}
+
+/// Computes the destination rect for an image within its view box, honoring
+/// `preserveAspectRatio`'s default "meet" (fit-within, centered) behavior.
+/// "slice" is not handled.
+fn fit_image_rect(
+    view_box: &usvg::ViewBox,
+    img_width: f64,
+    img_height: f64,
+) -> (f64, f64, f64, f64) {
+    let r = view_box.rect;
+    if view_box.aspect.align == usvg::Align::None
+        || img_width <= 0.0
+        || img_height <= 0.0
+    {
+        return (r.x(), r.y(), r.width(), r.height());
+    }
+
+    let scale = (r.width() / img_width).min(r.height() / img_height);
+    let w = img_width * scale;
+    let h = img_height * scale;
+    let x = r.x() + (r.width() - w) / 2.0;
+    let y = r.y() + (r.height() - h) / 2.0;
+    (x, y, w, h)
+}
+
+/// Walks the `usvg` tree, tessellating paths and recording clip masks in
+/// document order. `Group` nodes with a `clip_path` push a mask before
+/// descending into their children and pop it again afterwards, so nested
+/// `clipPath`s produce a nested `mask_stack` at draw time. `Group` nodes
+/// with `opacity < 1` are additionally wrapped in `PushLayer`/`PopLayer`,
+/// so the renderer composites the whole subtree as one translucent layer
+/// instead of alpha-blending each child against the group's background.
+fn visit_node(ctx: &mut TessCtx, node: &usvg::Node) {
+    match *node.borrow() {
+        usvg::NodeKind::Group(ref g) => {
+            let opacity = g.opacity.value();
+            let has_layer = opacity < 1.0;
+            if has_layer {
+                ctx.commands.push(DrawCommand::PushLayer {
+                    opacity: opacity as f32,
+                });
+            }
+
+            let mask_range = g.clip_path.as_ref().map(|clip_path| {
+                let index_start = ctx.mesh.indices.len() as u32;
+                for clip_node in clip_path.root.descendants() {
+                    if let usvg::NodeKind::Path(ref clip_p) = *clip_node.borrow()
+                    {
+                        let transform_idx = ctx.transform_idx_for(&clip_node);
+                        ctx.tessellate_clip_shape(clip_p, transform_idx);
+                    }
+                }
+                (index_start, ctx.mesh.indices.len() as u32 - index_start)
+            });
+
+            if let Some((index_start, index_count)) = mask_range {
+                ctx.commands.push(DrawCommand::PushMask {
+                    index_start,
+                    index_count,
+                });
+            }
+
+            for child in node.children() {
+                visit_node(ctx, &child);
+            }
+
+            if let Some((index_start, index_count)) = mask_range {
+                ctx.commands.push(DrawCommand::PopMask {
+                    index_start,
+                    index_count,
+                });
+            }
+
+            if has_layer {
+                ctx.commands.push(DrawCommand::PopLayer);
+            }
+        }
+        usvg::NodeKind::Path(ref p) => {
+            let transform_idx = ctx.transform_idx_for(node);
+            ctx.tessellate_path(p, transform_idx);
+        }
+        usvg::NodeKind::Image(ref img) => {
+            ctx.tessellate_image(img, node);
+        }
+        _ => {}
     }
 }
 
@@ -272,6 +519,144 @@ pub fn convert_path(p: &usvg::Path) -> PathConvIter {
     }
 }
 
+fn convert_fill_rule(rule: usvg::FillRule) -> tessellation::FillRule {
+    match rule {
+        usvg::FillRule::NonZero => tessellation::FillRule::NonZero,
+        usvg::FillRule::EvenOdd => tessellation::FillRule::EvenOdd,
+    }
+}
+
+fn convert_spread(spread: usvg::SpreadMethod) -> u32 {
+    match spread {
+        usvg::SpreadMethod::Pad => SPREAD_PAD,
+        usvg::SpreadMethod::Repeat => SPREAD_REPEAT,
+        usvg::SpreadMethod::Reflect => SPREAD_REFLECT,
+    }
+}
+
+fn push_stops(
+    stops: &[usvg::Stop],
+    gradient_stops: &mut Vec<GpuGradientStop>,
+) -> (u32, u32) {
+    let start = gradient_stops.len() as u32;
+    for stop in stops {
+        let c = stop.color;
+        gradient_stops.push(GpuGradientStop {
+            color: [
+                c.red as f32 / 255.0,
+                c.green as f32 / 255.0,
+                c.blue as f32 / 255.0,
+                stop.opacity.value() as f32,
+            ],
+            offset: stop.offset.value() as f32,
+            _pad: [0.0; 3],
+        });
+    }
+    (start, stops.len() as u32)
+}
+
+/// Inverts a `usvg::Transform`, falling back to identity if it's singular
+/// (degenerate `objectBoundingBox` gradients on a zero-area box).
+fn invert_transform(t: usvg::Transform) -> usvg::Transform {
+    let det = t.a * t.d - t.b * t.c;
+    if det.abs() < f64::EPSILON {
+        return usvg::Transform {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            e: 0.0,
+            f: 0.0,
+        };
+    }
+
+    usvg::Transform {
+        a: t.d / det,
+        b: -t.b / det,
+        c: -t.c / det,
+        d: t.a / det,
+        e: (t.c * t.f - t.d * t.e) / det,
+        f: (t.b * t.e - t.a * t.f) / det,
+    }
+}
+
+/// Builds the affine that maps a path vertex into gradient parameter space:
+/// `base.transform` maps gradient space to user space (identity for
+/// `userSpaceOnUse`, the bounding-box affine for `objectBoundingBox`), so a
+/// vertex already in user space needs its *inverse* applied first; then
+/// project onto the unit axis running from `(x1, y1)` to `(x2, y2)` so the
+/// resulting x component is `t`.
+fn push_linear_gradient(
+    gradient: &usvg::LinearGradient,
+    gradients: &mut Vec<GpuGradient>,
+    gradient_stops: &mut Vec<GpuGradientStop>,
+) -> u32 {
+    let t = invert_transform(gradient.base.transform);
+    let (x1, y1, x2, y2) = (gradient.x1, gradient.y1, gradient.x2, gradient.y2);
+
+    let dx = x2 - x1;
+    let dy = y2 - y1;
+    let len_sq = dx * dx + dy * dy;
+    let (ux, uy) = if len_sq > 0.0 {
+        (dx / len_sq, dy / len_sq)
+    } else {
+        (0.0, 0.0)
+    };
+
+    let a = ux * t.a + uy * t.b;
+    let c = ux * t.c + uy * t.d;
+    let e = ux * t.e + uy * t.f - (ux * x1 + uy * y1);
+
+    let (stops_start, stops_count) =
+        push_stops(&gradient.base.stops, gradient_stops);
+
+    gradients.push(GpuGradient {
+        matrix0: [a as f32, c as f32, e as f32, 0.0],
+        matrix1: [0.0, 0.0, 0.0, 0.0],
+        kind: GRADIENT_KIND_LINEAR,
+        spread: convert_spread(gradient.base.spread_method),
+        stops_start,
+        stops_count,
+    });
+
+    gradients.len() as u32 - 1
+}
+
+/// Builds the affine that maps a path vertex into gradient parameter space:
+/// apply the inverse of the gradient's own `transform` (see
+/// `invert_transform`), then map into the unit circle centered at the focal
+/// point so the resulting vector length is `t`.
+fn push_radial_gradient(
+    gradient: &usvg::RadialGradient,
+    gradients: &mut Vec<GpuGradient>,
+    gradient_stops: &mut Vec<GpuGradientStop>,
+) -> u32 {
+    let t = invert_transform(gradient.base.transform);
+    let (cx, cy, r) = (gradient.fx, gradient.fy, gradient.r.value());
+    let r = if r > 0.0 { r } else { 1.0 };
+
+    let a = t.a / r;
+    let c = t.c / r;
+    let e = (t.e - cx) / r;
+    let b = t.b / r;
+    let d = t.d / r;
+    let f = (t.f - cy) / r;
+
+    let (stops_start, stops_count) =
+        push_stops(&gradient.base.stops, gradient_stops);
+
+    gradients.push(GpuGradient {
+        matrix0: [a as f32, c as f32, e as f32, 0.0],
+        matrix1: [b as f32, d as f32, f as f32, 0.0],
+        kind: GRADIENT_KIND_RADIAL,
+        spread: convert_spread(gradient.base.spread_method),
+        stops_start,
+        stops_count,
+    });
+
+    gradients.len() as u32 - 1
+}
+
 pub fn convert_stroke(s: &usvg::Stroke) -> (GpuColor, StrokeOptions) {
     let color = match s.paint {
         usvg::Paint::Color(c) => GpuColor {
@@ -0,0 +1,119 @@
+//! GPU-facing data layouts shared between the tessellator and the render buffers
+//! built in `crate::util`.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuColor {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+unsafe impl bytemuck::Pod for GpuColor {}
+unsafe impl bytemuck::Zeroable for GpuColor {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuVertex {
+    pub position: [f32; 2],
+    pub prim_id: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuTransform {
+    pub data0: [f32; 4],
+    pub data1: [f32; 4],
+}
+
+/// `GpuPrimitive::fill_kind` values.
+pub const FILL_KIND_SOLID: u32 = 0;
+pub const FILL_KIND_GRADIENT: u32 = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuPrimitive {
+    pub color: [f32; 4],
+    pub transform_id: u32,
+    pub fill_kind: u32,
+    pub gradient_id: u32,
+    pub _pad: u32,
+}
+
+impl GpuPrimitive {
+    pub fn new(transform_id: u32, color: GpuColor) -> Self {
+        GpuPrimitive {
+            color: [
+                color.red as f32 / 255.0,
+                color.green as f32 / 255.0,
+                color.blue as f32 / 255.0,
+                color.alpha as f32 / 255.0,
+            ],
+            transform_id,
+            fill_kind: FILL_KIND_SOLID,
+            gradient_id: 0,
+            _pad: 0,
+        }
+    }
+
+    pub fn new_gradient(transform_id: u32, gradient_id: u32) -> Self {
+        GpuPrimitive {
+            color: [0.0; 4],
+            transform_id,
+            fill_kind: FILL_KIND_GRADIENT,
+            gradient_id,
+            _pad: 0,
+        }
+    }
+}
+
+/// `GpuGradient::kind` values.
+pub const GRADIENT_KIND_LINEAR: u32 = 0;
+pub const GRADIENT_KIND_RADIAL: u32 = 1;
+
+/// `GpuGradient::spread` values, mirroring `usvg::SpreadMethod`.
+pub const SPREAD_PAD: u32 = 0;
+pub const SPREAD_REPEAT: u32 = 1;
+pub const SPREAD_REFLECT: u32 = 2;
+
+/// A linear or radial gradient, resolved into the affine that maps a path
+/// vertex into gradient parameter space, plus the range of
+/// `GpuGradientStop`s that describe its color ramp.
+///
+/// `matrix0`/`matrix1` hold the affine's two rows as `[a, c, e, _]` and
+/// `[b, d, f, _]`: the first row produces the gradient-space x coordinate
+/// (`t` for a linear gradient), the second the y coordinate (combined with
+/// the first via `length()` for a radial gradient). Each row is padded out
+/// to four floats (the trailing component is unused) because WGSL's
+/// `vec3<f32>`/`vec4<f32>` both have 16-byte alignment in a storage buffer —
+/// using `vec3` on the shader side would silently shift every field after it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuGradient {
+    pub matrix0: [f32; 4],
+    pub matrix1: [f32; 4],
+    pub kind: u32,
+    pub spread: u32,
+    pub stops_start: u32,
+    pub stops_count: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuGradientStop {
+    pub color: [f32; 4],
+    pub offset: f32,
+    pub _pad: [f32; 3],
+}
+
+/// A vertex of a textured quad standing in for an embedded `<image>` node.
+/// Unlike `GpuVertex`, `transform_id` is carried directly rather than
+/// looked up through a `GpuPrimitive`, since each image draws through its
+/// own bind group rather than the shared prims storage buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuImageVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub transform_id: u32,
+}
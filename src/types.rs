@@ -0,0 +1,100 @@
+//! Crate-level render state: the globals uniform and the collection of
+//! buffers/bind groups `crate::util::build_buffers` produces.
+
+use winit::dpi::PhysicalSize;
+
+/// A per-fragment multiply + add applied to the resolved color (`color *
+/// mult + add`, clamped to `[0, 1]`), so a caller can recolor an
+/// already-tessellated scene — dark-mode tinting, fade transitions — by
+/// updating the globals uniform instead of rebuilding vertex/primitive
+/// buffers.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuColorTransform {
+    pub mult: [f32; 4],
+    pub add: [f32; 4],
+}
+
+impl GpuColorTransform {
+    pub const IDENTITY: GpuColorTransform = GpuColorTransform {
+        mult: [1.0, 1.0, 1.0, 1.0],
+        add: [0.0, 0.0, 0.0, 0.0],
+    };
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuGlobals {
+    pub zoom: f32,
+    /// Pads `pan` out to its required 8-byte alignment in the shader's
+    /// `Globals` uniform (`vec2<f32>` aligns to 8, not 4).
+    pub _pad0: f32,
+    pub pan: [f32; 2],
+    pub aspect_ratio: f32,
+    /// Pads `color_transform` out to its required 16-byte alignment in the
+    /// shader's `Globals` uniform (a struct containing `vec4<f32>` members
+    /// aligns to 16).
+    pub _pad1: [f32; 3],
+    pub color_transform: GpuColorTransform,
+}
+
+/// The single uniform the layer-composite pipeline reads: how much to scale
+/// down the offscreen layer's alpha by when blending it back onto its
+/// parent target.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuLayerGlobals {
+    pub opacity: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SceneGlobals {
+    pub zoom: f32,
+    pub pan: [f32; 2],
+    pub window_size: PhysicalSize<u32>,
+    pub wireframe: bool,
+    pub size_changed: bool,
+    pub color_transform: GpuColorTransform,
+}
+
+impl SceneGlobals {
+    /// Recolors the scene by updating its color transform in place. Callers
+    /// write the result to `BufferState::globals_ubo` (via
+    /// `queue.write_buffer`) each frame to apply it — no vertex or
+    /// primitive buffer rebuild required.
+    pub fn set_color_transform(&mut self, mult: [f32; 4], add: [f32; 4]) {
+        self.color_transform = GpuColorTransform { mult, add };
+    }
+}
+
+pub struct BufferState {
+    pub primitives: u64,
+    pub transforms: u64,
+    pub gradients: u64,
+    pub gradient_stops: u64,
+    pub ibo: wgpu::Buffer,
+    pub vbo: wgpu::Buffer,
+    pub prims_ssbo: wgpu::Buffer,
+    pub transforms_ssbo: wgpu::Buffer,
+    pub gradients_ssbo: wgpu::Buffer,
+    pub gradient_stops_ssbo: wgpu::Buffer,
+    pub globals_ubo: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// One embedded image's GPU resources: the draw's vertex range (shared
+/// `image_vbo`) paired with the texture/sampler bind group that range reads
+/// from.
+pub struct ImageDrawBinding {
+    pub vertex_start: u32,
+    pub vertex_count: u32,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// Buffers and per-image bind groups produced by `crate::util::build_image_buffers`.
+pub struct ImageBufferState {
+    pub image_vbo: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub draws: Vec<ImageDrawBinding>,
+}
@@ -1,15 +1,22 @@
 use crate::tessellator::{
     artifacts::TessellationData,
-    types::{GpuPrimitive, GpuTransform, GpuVertex},
+    types::{
+        GpuGradient, GpuGradientStop, GpuImageVertex, GpuPrimitive,
+        GpuTransform, GpuVertex,
+    },
 };
 
-use super::types::{BufferState, GpuGlobals, SceneGlobals};
+use super::types::{
+    BufferState, GpuColorTransform, GpuGlobals, ImageBufferState,
+    ImageDrawBinding, SceneGlobals,
+};
 
 use wgpu::{include_wgsl, util::DeviceExt, RenderPipeline};
 use winit::dpi::PhysicalSize;
 
 pub const WINDOW_SIZE: f32 = 800.0;
 pub const MSAA_SAMPLES: u32 = 4;
+pub const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
 // These mush match the uniform buffer sizes in the vertex shader.
 pub fn get_globals(svg_contents: &String) -> SceneGlobals {
     let opt = usvg::Options::default();
@@ -35,15 +42,55 @@ pub fn get_globals(svg_contents: &String) -> SceneGlobals {
         window_size: PhysicalSize::new(width as u32, height as u32),
         wireframe: false,
         size_changed: true,
+        color_transform: GpuColorTransform::IDENTITY,
     };
     scene
 }
 
-pub fn build_pipeline(
+/// The three pipeline variants used to composite nested `clipPath` masks
+/// through a shared stencil buffer, all built off the same vertex/fragment
+/// shaders, pipeline layout and vertex/primitive state.
+pub struct MaskPipelines {
+    /// Renders clip geometry with color writes disabled, incrementing the
+    /// stencil buffer from the parent mask's depth to the new nesting depth.
+    /// The stencil compare is `Equal` against the pipeline's stencil
+    /// reference (set to the *parent* depth), so only pixels already inside
+    /// the active parent mask are pulled in — nested `clipPath`s intersect
+    /// rather than union — and the increment is idempotent across triangles
+    /// that overlap within the same clip shape (a self-intersecting or
+    /// multi-subpath `clipPath` tessellates into triangles that can cover
+    /// the same pixel more than once): once a pixel is incremented past the
+    /// parent depth, it no longer matches the reference, so a second
+    /// overlapping triangle fails the compare and leaves it alone.
+    pub write_mask: RenderPipeline,
+    /// Renders the same clip geometry again with color writes disabled,
+    /// decrementing the stencil buffer back from the child depth to its
+    /// parent depth. Mirrors `write_mask`'s `Equal`-against-reference
+    /// idempotence, using the child depth as the reference instead.
+    pub clear_mask: RenderPipeline,
+    /// Renders ordinary fill/stroke geometry, passing only where the
+    /// stencil buffer equals the pipeline's current stencil reference.
+    pub draw: RenderPipeline,
+}
+
+fn stencil_face_state(
+    compare: wgpu::CompareFunction,
+    pass_op: wgpu::StencilOperation,
+) -> wgpu::StencilFaceState {
+    wgpu::StencilFaceState {
+        compare,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op,
+    }
+}
+
+pub fn build_pipelines(
     device: &wgpu::Device,
     buffers: &BufferState,
     wireframe: bool,
-) -> RenderPipeline {
+    target_format: wgpu::TextureFormat,
+) -> MaskPipelines {
     // Get shaders
     let vert_module =
         device.create_shader_module(&include_wgsl!("shaders/vert.wgsl"));
@@ -58,43 +105,193 @@ pub fn build_pipeline(
             label: None,
         });
 
-    let render_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
-        label: None,
+    let topology = match wireframe {
+        true => wgpu::PrimitiveTopology::LineList,
+        false => wgpu::PrimitiveTopology::TriangleList,
+    };
+
+    let make_pipeline =
+        |label: &str,
+         color_write_mask: wgpu::ColorWrites,
+         stencil: wgpu::StencilFaceState,
+         blend: Option<wgpu::BlendState>| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &vert_module,
+                    entry_point: "main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<GpuVertex>() as u64,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                offset: 0,
+                                format: wgpu::VertexFormat::Float32x2,
+                                shader_location: 0,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: 8,
+                                format: wgpu::VertexFormat::Uint32,
+                                shader_location: 1,
+                            },
+                        ],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &frag_module,
+                    entry_point: "main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: target_format,
+                        blend,
+                        write_mask: color_write_mask,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    front_face: wgpu::FrontFace::Ccw,
+                    strip_index_format: None,
+                    cull_mode: None,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: STENCIL_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState {
+                        front: stencil,
+                        back: stencil,
+                        read_mask: 0xff,
+                        write_mask: 0xff,
+                    },
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: MSAA_SAMPLES,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+
+    MaskPipelines {
+        write_mask: make_pipeline(
+            "write mask",
+            wgpu::ColorWrites::empty(),
+            stencil_face_state(
+                wgpu::CompareFunction::Equal,
+                wgpu::StencilOperation::IncrementClamp,
+            ),
+            None,
+        ),
+        clear_mask: make_pipeline(
+            "clear mask",
+            wgpu::ColorWrites::empty(),
+            stencil_face_state(
+                wgpu::CompareFunction::Equal,
+                wgpu::StencilOperation::DecrementClamp,
+            ),
+            None,
+        ),
+        draw: make_pipeline(
+            "draw",
+            wgpu::ColorWrites::ALL,
+            stencil_face_state(
+                wgpu::CompareFunction::Equal,
+                wgpu::StencilOperation::Keep,
+            ),
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+        ),
+    }
+}
+
+/// The pipeline (and the bind group layout it reads through) that composites
+/// an offscreen opacity-group layer back onto its parent target: a single
+/// fullscreen triangle that samples the layer's resolved texture and scales
+/// its alpha by the group's opacity.
+pub struct LayerPipelines {
+    pub composite: RenderPipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+pub fn build_layer_pipeline(
+    device: &wgpu::Device,
+    target_format: wgpu::TextureFormat,
+) -> LayerPipelines {
+    let vert_module =
+        device.create_shader_module(&include_wgsl!("shaders/layer_vert.wgsl"));
+    let frag_module =
+        device.create_shader_module(&include_wgsl!("shaders/layer_frag.wgsl"));
+
+    let bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Layer bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<crate::types::GpuLayerGlobals>()
+                                as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float {
+                            filterable: true,
+                        },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(
+                        wgpu::SamplerBindingType::Filtering,
+                    ),
+                    count: None,
+                },
+            ],
+        });
+
+    let pipeline_layout =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Layer pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+    let composite = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("layer composite"),
         layout: Some(&pipeline_layout),
         vertex: wgpu::VertexState {
             module: &vert_module,
             entry_point: "main",
-            buffers: &[wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<GpuVertex>() as u64,
-                step_mode: wgpu::VertexStepMode::Vertex,
-                attributes: &[
-                    wgpu::VertexAttribute {
-                        offset: 0,
-                        format: wgpu::VertexFormat::Float32x2,
-                        shader_location: 0,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: 8,
-                        format: wgpu::VertexFormat::Uint32,
-                        shader_location: 1,
-                    },
-                ],
-            }],
+            buffers: &[],
         },
         fragment: Some(wgpu::FragmentState {
             module: &frag_module,
             entry_point: "main",
             targets: &[wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Bgra8Unorm,
-                blend: None,
+                format: target_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                 write_mask: wgpu::ColorWrites::ALL,
             }],
         }),
         primitive: wgpu::PrimitiveState {
-            topology: match wireframe {
-                true => wgpu::PrimitiveTopology::LineList,
-                false => wgpu::PrimitiveTopology::TriangleList,
-            },
+            topology: wgpu::PrimitiveTopology::TriangleList,
             polygon_mode: wgpu::PolygonMode::Fill,
             front_face: wgpu::FrontFace::Ccw,
             strip_index_format: None,
@@ -102,20 +299,41 @@ pub fn build_pipeline(
             unclipped_depth: false,
             conservative: false,
         },
-        depth_stencil: None,
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: STENCIL_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState {
+                front: stencil_face_state(
+                    wgpu::CompareFunction::Equal,
+                    wgpu::StencilOperation::Keep,
+                ),
+                back: stencil_face_state(
+                    wgpu::CompareFunction::Equal,
+                    wgpu::StencilOperation::Keep,
+                ),
+                read_mask: 0xff,
+                write_mask: 0xff,
+            },
+            bias: wgpu::DepthBiasState::default(),
+        }),
         multisample: wgpu::MultisampleState {
             count: MSAA_SAMPLES,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
         multiview: None,
-    };
+    });
 
-    device.create_render_pipeline(&render_pipeline_descriptor)
+    LayerPipelines {
+        composite,
+        bind_group_layout,
+    }
 }
 
 pub fn build_buffers(
     device: &wgpu::Device,
+    queue: &wgpu::Queue,
     data: &TessellationData,
 ) -> BufferState {
     // Create vertex buffer object
@@ -135,6 +353,11 @@ pub fn build_buffers(
         (data.primitives.len() * std::mem::size_of::<GpuPrimitive>()) as u64;
     let transform_buffer_byte_size =
         (data.transforms.len() * std::mem::size_of::<GpuTransform>()) as u64;
+    let gradient_buffer_byte_size =
+        (data.gradients.len() * std::mem::size_of::<GpuGradient>()) as u64;
+    let gradient_stop_buffer_byte_size = (data.gradient_stops.len()
+        * std::mem::size_of::<GpuGradientStop>())
+        as u64;
     let globals_buffer_byte_size = std::mem::size_of::<GpuGlobals>() as u64;
 
     let prims_ssbo = device.create_buffer(&wgpu::BufferDescriptor {
@@ -145,6 +368,7 @@ pub fn build_buffers(
             | wgpu::BufferUsages::COPY_DST,
         mapped_at_creation: false,
     });
+    queue.write_buffer(&prims_ssbo, 0, bytemuck::cast_slice(&data.primitives));
 
     let transforms_ssbo = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Transforms ssbo"),
@@ -154,6 +378,27 @@ pub fn build_buffers(
             | wgpu::BufferUsages::COPY_DST,
         mapped_at_creation: false,
     });
+    queue.write_buffer(&transforms_ssbo, 0, bytemuck::cast_slice(&data.transforms));
+
+    let gradients_ssbo = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Gradients ssbo"),
+        size: gradient_buffer_byte_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    queue.write_buffer(&gradients_ssbo, 0, bytemuck::cast_slice(&data.gradients));
+
+    let gradient_stops_ssbo = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Gradient stops ssbo"),
+        size: gradient_stop_buffer_byte_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    queue.write_buffer(
+        &gradient_stops_ssbo,
+        0,
+        bytemuck::cast_slice(&data.gradient_stops),
+    );
 
     let globals_ubo = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Globals ubo"),
@@ -206,6 +451,34 @@ pub fn build_buffers(
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            gradient_buffer_byte_size,
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            gradient_stop_buffer_byte_size,
+                        ),
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -231,18 +504,246 @@ pub fn build_buffers(
                     transforms_ssbo.as_entire_buffer_binding(),
                 ),
             },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Buffer(
+                    gradients_ssbo.as_entire_buffer_binding(),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Buffer(
+                    gradient_stops_ssbo.as_entire_buffer_binding(),
+                ),
+            },
         ],
     });
 
     BufferState {
         primitives: data.primitives.len() as u64,
         transforms: data.transforms.len() as u64,
+        gradients: data.gradients.len() as u64,
+        gradient_stops: data.gradient_stops.len() as u64,
         ibo,
         vbo,
         prims_ssbo,
         transforms_ssbo,
+        gradients_ssbo,
+        gradient_stops_ssbo,
         globals_ubo,
         bind_group_layout,
         bind_group,
     }
 }
+
+/// Builds the pipeline that draws embedded `<image>` quads: a textured quad
+/// per image, sharing the globals/transforms bind group (group 0) with the
+/// solid/gradient pipelines but reading its texture from a per-image group 1.
+pub fn build_image_pipeline(
+    device: &wgpu::Device,
+    globals_bind_group_layout: &wgpu::BindGroupLayout,
+    image_bind_group_layout: &wgpu::BindGroupLayout,
+    target_format: wgpu::TextureFormat,
+) -> RenderPipeline {
+    let vert_module =
+        device.create_shader_module(&include_wgsl!("shaders/image_vert.wgsl"));
+    let frag_module =
+        device.create_shader_module(&include_wgsl!("shaders/image_frag.wgsl"));
+
+    let pipeline_layout =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Image pipeline layout"),
+            bind_group_layouts: &[
+                globals_bind_group_layout,
+                image_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("image"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &vert_module,
+            entry_point: "main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<GpuImageVertex>() as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        offset: 0,
+                        format: wgpu::VertexFormat::Float32x2,
+                        shader_location: 0,
+                    },
+                    wgpu::VertexAttribute {
+                        offset: 8,
+                        format: wgpu::VertexFormat::Float32x2,
+                        shader_location: 1,
+                    },
+                    wgpu::VertexAttribute {
+                        offset: 16,
+                        format: wgpu::VertexFormat::Uint32,
+                        shader_location: 2,
+                    },
+                ],
+            }],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &frag_module,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format: target_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            front_face: wgpu::FrontFace::Ccw,
+            strip_index_format: None,
+            cull_mode: None,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: STENCIL_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState {
+                front: wgpu::StencilFaceState {
+                    compare: wgpu::CompareFunction::Equal,
+                    fail_op: wgpu::StencilOperation::Keep,
+                    depth_fail_op: wgpu::StencilOperation::Keep,
+                    pass_op: wgpu::StencilOperation::Keep,
+                },
+                back: wgpu::StencilFaceState {
+                    compare: wgpu::CompareFunction::Equal,
+                    fail_op: wgpu::StencilOperation::Keep,
+                    depth_fail_op: wgpu::StencilOperation::Keep,
+                    pass_op: wgpu::StencilOperation::Keep,
+                },
+                read_mask: 0xff,
+                write_mask: 0xff,
+            },
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: MSAA_SAMPLES,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+/// Uploads each embedded image's pixels as its own texture and builds the
+/// per-image (texture, sampler) bind group the image pipeline reads from,
+/// alongside the single vertex buffer holding every image's quad.
+pub fn build_image_buffers(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    data: &TessellationData,
+) -> ImageBufferState {
+    let image_vbo = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Image vbo"),
+        contents: bytemuck::cast_slice(&data.image_vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    let bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Image bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float {
+                            filterable: true,
+                        },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(
+                        wgpu::SamplerBindingType::Filtering,
+                    ),
+                    count: None,
+                },
+            ],
+        });
+
+    let draws = data
+        .image_draws
+        .iter()
+        .map(|draw| {
+            let image = &data.images[draw.image_index as usize];
+
+            let texture = device.create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some("Image texture"),
+                    size: wgpu::Extent3d {
+                        width: image.width,
+                        height: image.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_DST,
+                },
+                &image.rgba,
+            );
+            let view =
+                texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+            let filter_mode = if image.smooth {
+                wgpu::FilterMode::Linear
+            } else {
+                wgpu::FilterMode::Nearest
+            };
+            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Image sampler"),
+                mag_filter: filter_mode,
+                min_filter: filter_mode,
+                ..Default::default()
+            });
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Image bind group"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            });
+
+            ImageDrawBinding {
+                vertex_start: draw.vertex_start,
+                vertex_count: draw.vertex_count,
+                bind_group,
+            }
+        })
+        .collect();
+
+    ImageBufferState {
+        image_vbo,
+        bind_group_layout,
+        draws,
+    }
+}
+